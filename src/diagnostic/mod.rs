@@ -0,0 +1,290 @@
+//! The diagnostic module defines the error reporting infrastructure shared by every compilation
+//! pass in `interpreter`, as well as the loader in `module`.
+//!
+//! A `DiagnosticResult<T>` is a value that may or may not have been computed successfully,
+//! paired with zero or more `ErrorMessage`s accumulated along the way. Passes are chained
+//! together with `bind`, and `deny` is used to stop the chain dead once an error has been
+//! recorded, mirroring `Result`'s `?` operator but retaining the messages seen so far.
+
+use std::fmt::Display;
+
+pub mod render;
+
+use crate::interpreter::Range;
+
+/// How serious a diagnostic is. This affects both how it is rendered and whether it causes
+/// `DiagnosticResult::deny` to abort a pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// Whether a `Label` points at the main cause of a diagnostic, or merely provides extra context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelStyle {
+    Primary,
+    Secondary,
+}
+
+/// A single span of source code annotated with a message, attached to a `Diagnostic`.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub range: Range,
+    pub message: String,
+    pub style: LabelStyle,
+}
+
+impl Label {
+    pub fn primary(range: Range, message: impl Into<String>) -> Self {
+        Self {
+            range,
+            message: message.into(),
+            style: LabelStyle::Primary,
+        }
+    }
+
+    pub fn secondary(range: Range, message: impl Into<String>) -> Self {
+        Self {
+            range,
+            message: message.into(),
+            style: LabelStyle::Secondary,
+        }
+    }
+}
+
+/// Describes where an `ErrorMessage` was raised: which module, and optionally which labelled
+/// spans inside that module's source. A diagnostic with no labels still prints, but falls back
+/// to naming just the module, for errors raised before any source text exists (e.g. a missing
+/// file).
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub module_path: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// A diagnostic that applies to an entire module, with no particular span singled out.
+    pub fn in_file(module_path: impl Display) -> Self {
+        Self {
+            module_path: module_path.to_string(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    /// A diagnostic whose primary cause is the given range inside the module.
+    pub fn at(module_path: impl Display, range: Range) -> Self {
+        Self::in_file(module_path).with_label(Label::primary(range, String::new()))
+    }
+
+    pub fn with_label(mut self, label: Label) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn primary_label(&self) -> Option<&Label> {
+        self.labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .or_else(|| self.labels.first())
+    }
+}
+
+/// A single machine-applicable edit: replace the text in `range` with `replacement`.
+/// An empty `range` is a pure insertion (e.g. a missing closing bracket); an empty
+/// `replacement` is a pure deletion.
+///
+/// `interpreter::brackets::process_brackets` is the real producer: an unclosed bracket raises one
+/// of these suggesting the matching closer be inserted at the end of the module.
+/// `render::tests` covers the render side of the contract (an inserted vs. a deleted vs. a
+/// replaced span) directly against hand-built `Suggestion`s, so that the three renderings stay
+/// pinned independently of whatever the real producer happens to emit.
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub range: Range,
+    pub replacement: String,
+}
+
+impl Suggestion {
+    pub fn insert(at: Range, text: impl Into<String>) -> Self {
+        Self {
+            range: at,
+            replacement: text.into(),
+        }
+    }
+
+    pub fn delete(range: Range) -> Self {
+        Self {
+            range,
+            replacement: String::new(),
+        }
+    }
+
+    pub fn replace(range: Range, text: impl Into<String>) -> Self {
+        Self {
+            range,
+            replacement: text.into(),
+        }
+    }
+}
+
+/// A single diagnostic message, with the location it was raised at, and any fix-its we can
+/// offer to resolve it without a human having to work out the edit themselves.
+#[derive(Debug, Clone)]
+pub struct ErrorMessage {
+    pub message: String,
+    pub severity: Severity,
+    pub diagnostic: Diagnostic,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl ErrorMessage {
+    pub fn new(message: String, severity: Severity, diagnostic: Diagnostic) -> Self {
+        Self {
+            message,
+            severity,
+            diagnostic,
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn with_suggestion(mut self, suggestion: Suggestion) -> Self {
+        self.suggestions.push(suggestion);
+        self
+    }
+}
+
+/// The result of running a compilation pass: a value, if one could be produced, alongside every
+/// `ErrorMessage` raised while producing it.
+#[derive(Debug)]
+pub struct DiagnosticResult<T> {
+    value: Option<T>,
+    messages: Vec<ErrorMessage>,
+}
+
+impl<T> DiagnosticResult<T> {
+    pub fn ok(value: T) -> Self {
+        Self {
+            value: Some(value),
+            messages: Vec::new(),
+        }
+    }
+
+    pub fn ok_with(value: T, messages: Vec<ErrorMessage>) -> Self {
+        Self {
+            value: Some(value),
+            messages,
+        }
+    }
+
+    pub fn fail(message: ErrorMessage) -> Self {
+        Self {
+            value: None,
+            messages: vec![message],
+        }
+    }
+
+    /// Chains another pass onto this result. If this result has no value, the next pass never
+    /// runs, but its messages (none, since it didn't run) are combined regardless; if it does
+    /// have a value, the next pass's messages are appended after ours.
+    pub fn bind<U>(self, f: impl FnOnce(T) -> DiagnosticResult<U>) -> DiagnosticResult<U> {
+        match self.value {
+            Some(value) => {
+                let next = f(value);
+                let mut messages = self.messages;
+                messages.extend(next.messages);
+                DiagnosticResult {
+                    value: next.value,
+                    messages,
+                }
+            }
+            None => DiagnosticResult {
+                value: None,
+                messages: self.messages,
+            },
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> DiagnosticResult<U> {
+        DiagnosticResult {
+            value: self.value.map(f),
+            messages: self.messages,
+        }
+    }
+
+    /// Stops the chain dead if any `Severity::Error` has been recorded so far, even if this
+    /// result still carries a (possibly partially recovered) value.
+    pub fn deny(mut self) -> Self {
+        if self.messages.iter().any(|m| m.severity == Severity::Error) {
+            self.value = None;
+        }
+        self
+    }
+
+    pub fn value(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    pub fn messages(&self) -> &[ErrorMessage] {
+        &self.messages
+    }
+
+    pub fn into_parts(self) -> (Option<T>, Vec<ErrorMessage>) {
+        (self.value, self.messages)
+    }
+}
+
+impl<T> From<Option<T>> for DiagnosticResult<T> {
+    fn from(value: Option<T>) -> Self {
+        Self {
+            value,
+            messages: Vec::new(),
+        }
+    }
+}
+
+/// Collects `ErrorMessage`s emitted while loading and compiling modules, so they can all be
+/// rendered together once a run has finished.
+#[derive(Debug, Default)]
+pub struct ErrorEmitter {
+    messages: Vec<ErrorMessage>,
+}
+
+impl ErrorEmitter {
+    pub fn process(&mut self, messages: Vec<ErrorMessage>) {
+        self.messages.extend(messages);
+    }
+
+    /// Drops every message previously raised against `module_path`, so a module can be reloaded
+    /// without its stale diagnostics lingering alongside the fresh ones. A long-lived emitter
+    /// (such as the `lsp` subsystem's, reloaded on every keystroke) needs this: otherwise a
+    /// diagnostic that no longer applies once the source is fixed is never cleared.
+    pub fn clear_module(&mut self, module_path: &str) {
+        self.messages
+            .retain(|message| message.diagnostic.module_path != module_path);
+    }
+
+    /// Unwraps a `DiagnosticResult`, recording its messages here and returning whatever value
+    /// (if any) it produced.
+    pub fn consume_diagnostic<T>(&mut self, result: DiagnosticResult<T>) -> Option<T> {
+        let (value, messages) = result.into_parts();
+        self.messages.extend(messages);
+        value
+    }
+
+    pub fn messages(&self) -> &[ErrorMessage] {
+        &self.messages
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.messages.iter().any(|m| m.severity == Severity::Error)
+    }
+}
@@ -0,0 +1,305 @@
+//! Renders `ErrorMessage`s as `codespan`-style text: a severity-coloured header, the offending
+//! source lines with a gutter of line numbers, carets underlining the labelled spans, and any
+//! trailing notes.
+//!
+//! Rendering degrades gracefully when the source text for a diagnostic's module was not
+//! retained (for example "cannot open file", which by definition has no lines to show): in that
+//! case we just print the message and the module path, with no snippet.
+
+use std::collections::HashMap;
+
+use super::{ErrorMessage, Label, LabelStyle, Severity};
+
+fn severity_colour(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "31",
+        Severity::Warning => "33",
+        Severity::Note => "34",
+    }
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+    }
+}
+
+/// Renders every message in `messages` against `sources`, a map from module path (as rendered by
+/// `Display`) to the lines of that module's source text.
+pub fn render(messages: &[ErrorMessage], sources: &HashMap<String, Vec<String>>) -> String {
+    let mut output = String::new();
+    for (i, message) in messages.iter().enumerate() {
+        if i != 0 {
+            output.push('\n');
+        }
+        render_one(message, sources, &mut output);
+    }
+    output
+}
+
+fn render_one(message: &ErrorMessage, sources: &HashMap<String, Vec<String>>, output: &mut String) {
+    let colour = severity_colour(message.severity);
+    output.push_str(&format!(
+        "\x1b[1;{colour}m{}\x1b[0m: {}\n",
+        severity_name(message.severity),
+        message.message
+    ));
+
+    let lines = sources.get(&message.diagnostic.module_path);
+    match (lines, message.diagnostic.primary_label()) {
+        (Some(lines), Some(primary)) => {
+            output.push_str(&format!(
+                "  \x1b[1;34m-->\x1b[0m {}:{}:{}\n",
+                message.diagnostic.module_path,
+                primary.range.start.line + 1,
+                primary.range.start.col + 1
+            ));
+            for label in &message.diagnostic.labels {
+                render_label(label, lines, colour, output);
+            }
+        }
+        _ => {
+            output.push_str(&format!("  --> {}\n", message.diagnostic.module_path));
+        }
+    }
+
+    for note in &message.diagnostic.notes {
+        output.push_str(&format!("  \x1b[1;34mnote\x1b[0m: {}\n", note));
+    }
+
+    for suggestion in &message.suggestions {
+        if suggestion.replacement.is_empty() {
+            output.push_str("  \x1b[1;32mhelp\x1b[0m: remove this\n");
+        } else if suggestion.range.start == suggestion.range.end {
+            output.push_str(&format!(
+                "  \x1b[1;32mhelp\x1b[0m: insert `{}`\n",
+                suggestion.replacement
+            ));
+        } else {
+            output.push_str(&format!(
+                "  \x1b[1;32mhelp\x1b[0m: replace with `{}`\n",
+                suggestion.replacement
+            ));
+        }
+    }
+}
+
+fn render_label(label: &Label, lines: &[String], colour: &str, output: &mut String) {
+    let gutter_colour = match label.style {
+        LabelStyle::Primary => colour,
+        LabelStyle::Secondary => "34",
+    };
+    let start_line = label.range.start.line as usize;
+    let end_line = label.range.end.line as usize;
+    let width = (end_line + 1).to_string().len();
+
+    for line_number in start_line..=end_line {
+        let Some(text) = lines.get(line_number) else {
+            continue;
+        };
+        output.push_str(&format!(
+            "\x1b[1;34m{:>width$} | \x1b[0m{}\n",
+            line_number + 1,
+            text,
+            width = width
+        ));
+
+        let (underline_start, underline_end) = if start_line == end_line {
+            (label.range.start.col as usize, label.range.end.col as usize)
+        } else if line_number == start_line {
+            (label.range.start.col as usize, text.len())
+        } else if line_number == end_line {
+            (0, label.range.end.col as usize)
+        } else {
+            (0, text.len())
+        };
+
+        let underline_char = match label.style {
+            LabelStyle::Primary => '^',
+            LabelStyle::Secondary => '-',
+        };
+        output.push_str(&format!(
+            "\x1b[1;34m{:>width$} | \x1b[0m{}\x1b[1;{gutter_colour}m{}\x1b[0m",
+            "",
+            " ".repeat(underline_start),
+            underline_char.to_string().repeat(underline_end.saturating_sub(underline_start).max(1)),
+            width = width,
+            gutter_colour = gutter_colour
+        ));
+        if !label.message.is_empty() {
+            output.push_str(&format!(" {}", label.message));
+        }
+        output.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Diagnostic;
+    use crate::interpreter::{Location, Range};
+
+    fn range(start_line: u32, start_col: u32, end_line: u32, end_col: u32) -> Range {
+        Range {
+            start: Location::new(start_line, start_col),
+            end: Location::new(end_line, end_col),
+        }
+    }
+
+    /// Strips `\x1b[...m` colour codes so assertions can check the visible text layout without
+    /// having to spell out every escape sequence.
+    fn strip_ansi(text: &str) -> String {
+        let mut result = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' && chars.peek() == Some(&'[') {
+                chars.next();
+                for c in chars.by_ref() {
+                    if c == 'm' {
+                        break;
+                    }
+                }
+            } else {
+                result.push(c);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn render_label_underlines_a_single_line_span() {
+        let lines = vec![String::from("let x = 1 + y;")];
+        let label = Label::primary(range(0, 8, 0, 13), "expected a number");
+        let mut output = String::new();
+
+        render_label(&label, &lines, "31", &mut output);
+        let output = strip_ansi(&output);
+
+        assert!(output.contains("1 | let x = 1 + y;"));
+        // Eight spaces to reach column 8, then five carets for columns 8..13.
+        assert!(output.contains(&format!("{}{}", " ".repeat(8), "^".repeat(5))));
+        assert!(output.ends_with("expected a number\n"));
+    }
+
+    #[test]
+    fn render_label_widens_across_every_line_of_a_multi_line_span() {
+        let lines = vec![
+            String::from("fn f() {"),
+            String::from("    1 +"),
+            String::from("}"),
+        ];
+        let label = Label::primary(range(0, 6, 2, 1), "unclosed delimiter");
+        let mut output = String::new();
+
+        render_label(&label, &lines, "31", &mut output);
+        let output = strip_ansi(&output);
+
+        // Every line of the span is printed, each with its own gutter and underline.
+        assert!(output.contains("1 | fn f() {"));
+        assert!(output.contains("2 |     1 +"));
+        assert!(output.contains("3 | }"));
+        // Every line's underline carries the label's message, not just the last one.
+        let message = " unclosed delimiter\n";
+        // First line: underlined from its start column to the end of the line.
+        assert!(output.contains(&format!("{}{}{}", " ".repeat(6), "^".repeat(2), message)));
+        // Middle line: underlined in full, since neither endpoint falls on it.
+        assert!(output.contains(&format!("{}{}", "^".repeat("    1 +".len()), message)));
+        // Last line: underlined from the start of the line to its end column.
+        assert!(output.contains(&format!("{}{}", "^", message)));
+    }
+
+    #[test]
+    fn render_one_falls_back_to_the_module_path_when_the_source_was_never_retained() {
+        let message = ErrorMessage::new(
+            String::from("cannot open file"),
+            Severity::Error,
+            Diagnostic::in_file("missing"),
+        );
+        let mut output = String::new();
+
+        render_one(&message, &HashMap::new(), &mut output);
+        let output = strip_ansi(&output);
+
+        assert!(output.contains("cannot open file"));
+        assert!(output.contains("--> missing"));
+        assert!(!output.contains(" | "));
+    }
+
+    #[test]
+    fn render_one_prints_an_insertion_suggestion_as_insert() {
+        use crate::diagnostic::Suggestion;
+
+        let message = ErrorMessage::new(
+            String::from("unclosed bracket"),
+            Severity::Error,
+            Diagnostic::in_file("missing"),
+        )
+        .with_suggestion(Suggestion::insert(range(0, 4, 0, 4), ")"));
+        let mut output = String::new();
+
+        render_one(&message, &HashMap::new(), &mut output);
+        let output = strip_ansi(&output);
+
+        assert!(output.contains("help: insert `)`"));
+    }
+
+    #[test]
+    fn render_one_prints_a_replacement_suggestion_as_replace_with() {
+        use crate::diagnostic::Suggestion;
+
+        let message = ErrorMessage::new(
+            String::from("wrong token"),
+            Severity::Error,
+            Diagnostic::in_file("missing"),
+        )
+        .with_suggestion(Suggestion::replace(range(0, 4, 0, 5), ")"));
+        let mut output = String::new();
+
+        render_one(&message, &HashMap::new(), &mut output);
+        let output = strip_ansi(&output);
+
+        assert!(output.contains("help: replace with `)`"));
+    }
+
+    #[test]
+    fn render_one_prints_a_deletion_suggestion_as_remove_this() {
+        use crate::diagnostic::Suggestion;
+
+        let message = ErrorMessage::new(
+            String::from("stray token"),
+            Severity::Error,
+            Diagnostic::in_file("missing"),
+        )
+        .with_suggestion(Suggestion::delete(range(0, 4, 0, 5)));
+        let mut output = String::new();
+
+        render_one(&message, &HashMap::new(), &mut output);
+        let output = strip_ansi(&output);
+
+        assert!(output.contains("help: remove this"));
+    }
+
+    #[test]
+    fn render_label_distinguishes_primary_from_secondary_styling() {
+        let lines = vec![String::from("x")];
+
+        let mut primary_output = String::new();
+        render_label(&Label::primary(range(0, 0, 0, 1), ""), &lines, "31", &mut primary_output);
+        assert!(primary_output.contains("\x1b[1;31m"));
+        assert!(strip_ansi(&primary_output).contains('^'));
+
+        let mut secondary_output = String::new();
+        render_label(
+            &Label::secondary(range(0, 0, 0, 1), ""),
+            &lines,
+            "31",
+            &mut secondary_output,
+        );
+        // The underline itself uses "34" (blue) for a secondary label, never "31".
+        assert!(!secondary_output.contains("\x1b[1;31m"));
+        assert!(strip_ansi(&secondary_output).contains('-'));
+        assert!(!strip_ansi(&secondary_output).contains('^'));
+    }
+}
@@ -0,0 +1,246 @@
+//! Fingerprint-based incremental recompilation for `ModuleLoader`.
+//!
+//! Reverifying a proof that hasn't changed, and whose dependencies haven't changed either, is
+//! pure waste, so `ModuleLoader` keeps a small dependency-aware cache: a content fingerprint of
+//! each module's source lines, the set of modules it transitively includes, and whether it was
+//! recompiled this run. A module is only recompiled if its own fingerprint changed, or one of
+//! its dependencies was recompiled. This table can be persisted to a sidecar file, but only the
+//! fingerprints and dependency sets are saved, not the compiled modules: a module that isn't
+//! already in memory is compiled even when the sidecar says it is unchanged.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io,
+    path::Path,
+};
+
+use crate::module::ModulePath;
+
+pub type Fingerprint = u64;
+
+/// Hashes the content of a module's source lines into a `Fingerprint`. Two calls with the same
+/// lines always produce the same fingerprint; that's all incremental recompilation needs.
+pub fn fingerprint(lines: &[String]) -> Fingerprint {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for line in lines {
+        line.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[derive(Debug, Clone, Default)]
+struct CacheEntry {
+    fingerprint: Fingerprint,
+    deps: Vec<ModulePath>,
+}
+
+/// Tracks, for each module seen so far, the fingerprint it was last compiled with and the
+/// modules it transitively depends on, forming an explicit dependency DAG used to decide what
+/// to invalidate when a module changes.
+#[derive(Debug, Default)]
+pub struct IncrementalCache {
+    entries: HashMap<ModulePath, CacheEntry>,
+}
+
+impl IncrementalCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `module_path` needs recompiling: either we have never seen it, its fingerprint
+    /// has changed since last time, or one of its dependencies was recompiled this run.
+    pub fn is_stale(
+        &self,
+        module_path: &ModulePath,
+        fingerprint: Fingerprint,
+        recompiled_this_run: &std::collections::HashSet<ModulePath>,
+    ) -> bool {
+        match self.entries.get(module_path) {
+            None => true,
+            Some(entry) => {
+                entry.fingerprint != fingerprint
+                    || entry
+                        .deps
+                        .iter()
+                        .any(|dep| recompiled_this_run.contains(dep))
+            }
+        }
+    }
+
+    /// The dependency set recorded the last time `module_path` was compiled, if we've seen it
+    /// before, or an empty slice if we haven't. `module::ModuleLoader::load` uses it to extend a
+    /// module's direct includes to the full set of modules it transitively depends on.
+    pub fn deps(&self, module_path: &ModulePath) -> &[ModulePath] {
+        self.entries
+            .get(module_path)
+            .map_or(&[], |entry| entry.deps.as_slice())
+    }
+
+    /// Records the result of (re)compiling `module_path`, so future invocations can tell
+    /// whether it needs recompiling again.
+    pub fn record(&mut self, module_path: ModulePath, fingerprint: Fingerprint, deps: Vec<ModulePath>) {
+        self.entries.insert(module_path, CacheEntry { fingerprint, deps });
+    }
+
+    /// Loads a previously `save`d cache from a sidecar file. A missing or unreadable file just
+    /// produces an empty cache, since that only costs us a cold recompile of everything.
+    pub fn load(path: &Path) -> Self {
+        let Ok(text) = fs::read_to_string(path) else {
+            return Self::new();
+        };
+
+        let mut entries = HashMap::new();
+        for line in text.lines() {
+            let mut fields = line.split('\t');
+            let (Some(module_path), Some(fingerprint), Some(deps)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let Ok(fingerprint) = fingerprint.parse() else {
+                continue;
+            };
+            let deps = if deps.is_empty() {
+                Vec::new()
+            } else {
+                deps.split(':').map(parse_module_path).collect()
+            };
+            entries.insert(
+                parse_module_path(module_path),
+                CacheEntry { fingerprint, deps },
+            );
+        }
+        Self { entries }
+    }
+
+    /// Persists this cache's fingerprints and dependency sets to a sidecar file. Compiled modules
+    /// are not saved, so a later cold start still compiles every module it loads.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut text = String::new();
+        for (module_path, entry) in &self.entries {
+            let deps = entry
+                .deps
+                .iter()
+                .map(ModulePath::escaped)
+                .collect::<Vec<_>>()
+                .join(":");
+            text.push_str(&format!("{}\t{}\t{}\n", module_path.escaped(), entry.fingerprint, deps));
+        }
+        fs::write(path, text)
+    }
+}
+
+/// `/` and `:` are the sidecar's own path- and deps-list separators, and `\t` separates its three
+/// fields, so a segment containing any of them would otherwise corrupt the line it's written
+/// into; `ModulePath::escaped`/`from_escaped` (the same escaping `module::ModuleLoader` uses to
+/// key its `sources` map and `Diagnostic::module_path`, for the identical reason) handle that.
+fn parse_module_path(text: &str) -> ModulePath {
+    ModulePath::from_escaped(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::module::test_path as path;
+
+    #[test]
+    fn a_module_never_seen_before_is_stale() {
+        let cache = IncrementalCache::new();
+        assert!(cache.is_stale(&path("a"), 123, &HashSet::new()));
+    }
+
+    #[test]
+    fn a_module_with_an_unchanged_fingerprint_and_no_recompiled_deps_is_not_stale() {
+        let mut cache = IncrementalCache::new();
+        cache.record(path("a"), 123, vec![path("b")]);
+
+        assert!(!cache.is_stale(&path("a"), 123, &HashSet::new()));
+        assert_eq!(cache.deps(&path("a")).to_vec(), vec![path("b")]);
+    }
+
+    #[test]
+    fn a_module_whose_fingerprint_changed_is_stale() {
+        let mut cache = IncrementalCache::new();
+        cache.record(path("a"), 123, Vec::new());
+
+        assert!(cache.is_stale(&path("a"), 456, &HashSet::new()));
+    }
+
+    #[test]
+    fn a_module_whose_dependency_was_recompiled_this_run_is_stale_even_with_an_unchanged_fingerprint() {
+        let mut cache = IncrementalCache::new();
+        cache.record(path("a"), 123, vec![path("b")]);
+
+        let mut recompiled_this_run = HashSet::new();
+        recompiled_this_run.insert(path("b"));
+
+        assert!(cache.is_stale(&path("a"), 123, &recompiled_this_run));
+    }
+
+    #[test]
+    fn recording_another_module_does_not_affect_unrelated_entries() {
+        let mut cache = IncrementalCache::new();
+        cache.record(path("a"), 123, vec![path("b")]);
+        cache.record(path("c"), 789, Vec::new());
+
+        assert!(!cache.is_stale(&path("a"), 123, &HashSet::new()));
+        assert_eq!(cache.deps(&path("a")).to_vec(), vec![path("b")]);
+    }
+
+    #[test]
+    fn save_then_load_round_trips_fingerprints_and_deps() {
+        // `c/d` here is a genuine two-segment module path (a module nested under a directory),
+        // not a single segment containing a slash.
+        let nested = ModulePath(vec!["c".to_string(), "d".to_string()]);
+
+        let mut cache = IncrementalCache::new();
+        cache.record(path("a"), 123, vec![path("b"), nested.clone()]);
+        cache.record(path("b"), 456, Vec::new());
+
+        let file = std::env::temp_dir().join(format!(
+            "shoumei-incremental-cache-test-{:?}",
+            std::thread::current().id()
+        ));
+        cache.save(&file).unwrap();
+        let loaded = IncrementalCache::load(&file);
+        fs::remove_file(&file).unwrap();
+
+        assert!(!loaded.is_stale(&path("a"), 123, &HashSet::new()));
+        assert_eq!(loaded.deps(&path("a")).to_vec(), vec![path("b"), nested]);
+        assert!(!loaded.is_stale(&path("b"), 456, &HashSet::new()));
+        assert!(loaded.deps(&path("b")).is_empty());
+    }
+
+    #[test]
+    fn save_then_load_round_trips_a_segment_containing_the_sidecars_own_separators() {
+        // Nothing stops a `ModulePath` segment from containing `/`, `:` or `\t` -- its field is
+        // public and nothing validates it. Escaping has to hold for such a segment too.
+        let awkward = ModulePath(vec![String::from("weird\tname:with/chars%too")]);
+
+        let mut cache = IncrementalCache::new();
+        cache.record(awkward.clone(), 123, vec![path("b")]);
+        cache.record(path("b"), 456, vec![awkward.clone()]);
+
+        let file = std::env::temp_dir().join(format!(
+            "shoumei-incremental-cache-escaping-test-{:?}",
+            std::thread::current().id()
+        ));
+        cache.save(&file).unwrap();
+        let loaded = IncrementalCache::load(&file);
+        fs::remove_file(&file).unwrap();
+
+        assert!(!loaded.is_stale(&awkward, 123, &HashSet::new()));
+        assert_eq!(loaded.deps(&awkward).to_vec(), vec![path("b")]);
+        assert_eq!(loaded.deps(&path("b")).to_vec(), vec![awkward]);
+    }
+
+    #[test]
+    fn loading_a_missing_sidecar_produces_an_empty_cache() {
+        let cache = IncrementalCache::load(&std::env::temp_dir().join("shoumei-incremental-cache-does-not-exist"));
+        assert!(cache.is_stale(&path("a"), 123, &HashSet::new()));
+    }
+}
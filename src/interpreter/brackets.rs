@@ -0,0 +1,131 @@
+//! Bracket matching over the whole module's token stream (spanning every line, not just one
+//! `indent::Line` at a time): checks that every `(`, `[` and `{` is closed, in order, by the
+//! matching `)`, `]` or `}`.
+//!
+//! Both kinds of mismatch recover instead of aborting the pass: a closing bracket with nothing
+//! matching it on the stack is reported and dropped from the stream, so whatever follows it is
+//! still processed; an opening bracket that's never closed is reported once, with a suggestion to
+//! insert the matching closer at the end of the module.
+
+use super::indent::{Line, TokenBlock};
+use super::lexer::TokenKind;
+use super::{Location, ModulePath, Range};
+use crate::diagnostic::Suggestion;
+use crate::{Diagnostic, DiagnosticResult, ErrorMessage, Severity};
+
+fn closer_for(open: char) -> char {
+    match open {
+        '(' => ')',
+        '[' => ']',
+        '{' => '}',
+        _ => unreachable!("closer_for is only ever called with an opening bracket"),
+    }
+}
+
+fn is_open(c: char) -> bool {
+    matches!(c, '(' | '[' | '{')
+}
+
+fn is_close(c: char) -> bool {
+    matches!(c, ')' | ']' | '}')
+}
+
+pub fn process_brackets(module_path: &ModulePath, token_block: TokenBlock) -> DiagnosticResult<TokenBlock> {
+    let mut messages = Vec::new();
+    let mut stack: Vec<(char, Range)> = Vec::new();
+    let mut result: TokenBlock = Vec::new();
+
+    for line in token_block {
+        let mut kept = Vec::new();
+        for token in line.tokens {
+            let TokenKind::Symbol(c) = token.kind else {
+                kept.push(token);
+                continue;
+            };
+
+            if is_open(c) {
+                stack.push((c, token.range));
+                kept.push(token);
+            } else if is_close(c) {
+                match stack.last() {
+                    Some((open, _)) if closer_for(*open) == c => {
+                        stack.pop();
+                        kept.push(token);
+                    }
+                    _ => messages.push(ErrorMessage::new(
+                        format!("unexpected closing bracket `{c}`"),
+                        Severity::Error,
+                        Diagnostic::at(module_path.escaped(), token.range),
+                    )),
+                }
+            } else {
+                kept.push(token);
+            }
+        }
+        result.push(Line { indent: line.indent, tokens: kept });
+    }
+
+    let end_of_module = result
+        .last()
+        .and_then(|line| line.tokens.last())
+        .map_or(Location::new(0, 0), |token| token.range.end);
+
+    for (open, range) in stack.into_iter().rev() {
+        let closer = closer_for(open);
+        messages.push(
+            ErrorMessage::new(
+                format!("unclosed bracket `{open}`"),
+                Severity::Error,
+                Diagnostic::at(module_path.escaped(), range),
+            )
+            .with_suggestion(Suggestion::insert(
+                Range { start: end_of_module, end: end_of_module },
+                closer.to_string(),
+            )),
+        );
+    }
+
+    DiagnosticResult::ok_with(result, messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::indent::process_indent;
+    use super::super::lexer::lex;
+    use super::*;
+    use super::super::test_path as path;
+
+    fn indented(line: &str) -> TokenBlock {
+        let tokens = lex(&path("a"), vec![String::from(line)]).into_parts().0.unwrap();
+        process_indent(&path("a"), tokens).into_parts().0.unwrap()
+    }
+
+    #[test]
+    fn matched_brackets_produce_no_diagnostics() {
+        let result = process_brackets(&path("a"), indented("theorem t : f(g(x))"));
+        assert!(result.messages().is_empty());
+    }
+
+    #[test]
+    fn a_stray_closing_bracket_is_reported_and_dropped() {
+        let result = process_brackets(&path("a"), indented("theorem t : f(x))"));
+
+        assert_eq!(result.messages().len(), 1);
+        assert!(result.messages()[0].message.contains("unexpected closing bracket"));
+        // The stray `)` is dropped; the matched pair around `x` survives.
+        let kept: Vec<_> = result.value().unwrap().iter().flat_map(|line| line.tokens.iter()).collect();
+        assert_eq!(kept.iter().filter(|token| matches!(token.kind, TokenKind::Symbol(')'))).count(), 1);
+    }
+
+    #[test]
+    fn an_unclosed_bracket_suggests_inserting_the_matching_closer() {
+        let result = process_brackets(&path("a"), indented("theorem t : f(x"));
+
+        assert_eq!(result.messages().len(), 1);
+        let message = &result.messages()[0];
+        assert!(message.message.contains("unclosed bracket"));
+        assert_eq!(message.suggestions.len(), 1);
+        assert_eq!(message.suggestions[0].replacement, ")");
+        assert_eq!(message.suggestions[0].range.start, message.suggestions[0].range.end);
+    }
+}
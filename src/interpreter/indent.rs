@@ -0,0 +1,63 @@
+//! Groups the flat token stream from `lexer` into one `Line` per source line, each carrying its
+//! own indentation depth (the column of its first token).
+//!
+//! `parser` only looks at zero-indent lines for now, so this deliberately doesn't build a nested
+//! block tree yet -- there's nothing in the grammar that would consume one. Recording each line's
+//! depth alongside its tokens is enough for that, and leaves room for a later grammar to nest
+//! lines by indent without this pass's output shape changing.
+
+use super::lexer::Token;
+use super::ModulePath;
+use crate::DiagnosticResult;
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub indent: usize,
+    pub tokens: Vec<Token>,
+}
+
+pub type TokenBlock = Vec<Line>;
+
+pub fn process_indent(_module_path: &ModulePath, tokens: Vec<Token>) -> DiagnosticResult<TokenBlock> {
+    let mut lines: Vec<Line> = Vec::new();
+
+    for token in tokens {
+        let line_number = token.range.start.line;
+        let continues_current_line = lines
+            .last()
+            .and_then(|line: &Line| line.tokens.last())
+            .is_some_and(|last| last.range.start.line == line_number);
+
+        if continues_current_line {
+            lines.last_mut().unwrap().tokens.push(token);
+        } else {
+            let indent = token.range.start.col as usize;
+            lines.push(Line { indent, tokens: vec![token] });
+        }
+    }
+
+    DiagnosticResult::ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::lexer::lex;
+    use super::*;
+    use super::super::test_path as path;
+
+    #[test]
+    fn groups_tokens_by_source_line_and_records_each_lines_indent() {
+        let tokens = lex(&path("a"), vec![String::from("theorem t : true"), String::from("  theorem u : true")])
+            .into_parts()
+            .0
+            .unwrap();
+
+        let lines = process_indent(&path("a"), tokens).into_parts().0.unwrap();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].indent, 0);
+        assert_eq!(lines[0].tokens.len(), 4);
+        assert_eq!(lines[1].indent, 2);
+        assert_eq!(lines[1].tokens.len(), 4);
+    }
+}
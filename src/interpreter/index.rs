@@ -0,0 +1,83 @@
+//! Builds the qualified symbol table for a module: one `QualifiedName` per declared theorem,
+//! catching the one structural error `parser` can't see on its own -- the same name declared
+//! twice in a module.
+
+use std::collections::HashMap;
+
+use super::parser::ModuleP;
+use super::types::ProjectTypesC;
+use super::{ModulePath, QualifiedName};
+use crate::{Diagnostic, DiagnosticResult, ErrorMessage, Label, Severity};
+
+pub type IndexC = Vec<QualifiedName>;
+
+pub fn index(
+    module_path: &ModulePath,
+    module: &ModuleP,
+    project_types: &ProjectTypesC,
+) -> DiagnosticResult<IndexC> {
+    // Nothing in the grammar `parser` recognises can reference a name from another module yet,
+    // so there's nothing to resolve `project_types` against; it's threaded through so that once
+    // such a reference exists, this signature doesn't need to change again.
+    let _ = project_types.get(module_path);
+
+    let mut seen: HashMap<&str, &QualifiedName> = HashMap::new();
+    let mut messages = Vec::new();
+    let mut names = Vec::new();
+
+    for theorem in &module.theorems {
+        if let Some(previous) = seen.get(theorem.name.name.as_str()) {
+            messages.push(ErrorMessage::new(
+                format!("`{}` is already declared in this module", theorem.name.name),
+                Severity::Error,
+                Diagnostic::at(module_path.escaped(), theorem.name.range)
+                    .with_label(Label::secondary(previous.range, "first declared here")),
+            ));
+            continue;
+        }
+        seen.insert(theorem.name.name.as_str(), &theorem.name);
+        names.push(theorem.name.clone());
+    }
+
+    DiagnosticResult::ok_with(names, messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_path as path;
+    use crate::interpreter::parser::TheoremP;
+    use crate::interpreter::{Location, Range};
+
+    fn theorem(name: &str, line: u32) -> TheoremP {
+        TheoremP {
+            name: QualifiedName {
+                module_path: path("a"),
+                name: String::from(name),
+                range: Range::from(Location::new(line, 0)),
+            },
+            statement: String::from("true"),
+        }
+    }
+
+    #[test]
+    fn indexes_every_distinct_theorem() {
+        let module = ModuleP { includes: Vec::new(), theorems: vec![theorem("t", 0), theorem("u", 1)] };
+
+        let result = index(&path("a"), &module, &ProjectTypesC::new());
+
+        assert!(result.messages().is_empty());
+        assert_eq!(result.value().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn a_name_declared_twice_is_reported_and_only_indexed_once() {
+        let module = ModuleP { includes: Vec::new(), theorems: vec![theorem("t", 0), theorem("t", 1)] };
+
+        let result = index(&path("a"), &module, &ProjectTypesC::new());
+
+        assert_eq!(result.messages().len(), 1);
+        assert!(result.messages()[0].message.contains("already declared"));
+        assert_eq!(result.value().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,161 @@
+//! Lexing: turns each source line into a flat stream of `Token`s.
+//!
+//! An unterminated string literal is reported and then recovered from by treating the rest of
+//! the line as its content, so a mistake on one line doesn't stop the rest of the module from
+//! being lexed -- later passes still see a token for every line, just one the parser may go on
+//! to reject on its own terms.
+
+use super::{Location, ModulePath, Range};
+use crate::{Diagnostic, DiagnosticResult, ErrorMessage, Severity};
+
+/// Punctuation that is always its own token, never absorbed into a `Word`.
+const SYMBOLS: &[char] = &['(', ')', '[', ']', '{', '}', ':', ';', ','];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A maximal run of characters that are neither whitespace, a `SYMBOLS` entry, nor `"`.
+    /// Covers both identifiers and keywords (`include`, `theorem`); `parser` is what tells them
+    /// apart.
+    Word(String),
+    /// The content between a pair of `"` delimiters, with the delimiters themselves stripped.
+    Str(String),
+    Symbol(char),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub range: Range,
+}
+
+pub fn lex(module_path: &ModulePath, lines: Vec<String>) -> DiagnosticResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut messages = Vec::new();
+
+    for (line_number, line) in lines.iter().enumerate() {
+        let line_number = line_number as u32;
+        let chars: Vec<char> = line.chars().collect();
+        let mut col = 0usize;
+
+        while col < chars.len() {
+            let c = chars[col];
+
+            if c.is_whitespace() {
+                col += 1;
+                continue;
+            }
+
+            if c == '"' {
+                let start = col;
+                col += 1;
+                let mut text = String::new();
+                let mut closed = false;
+                while col < chars.len() {
+                    if chars[col] == '"' {
+                        closed = true;
+                        col += 1;
+                        break;
+                    }
+                    text.push(chars[col]);
+                    col += 1;
+                }
+                let range = Range {
+                    start: Location::new(line_number, start as u32),
+                    end: Location::new(line_number, col as u32),
+                };
+                if !closed {
+                    messages.push(ErrorMessage::new(
+                        String::from("unterminated string literal"),
+                        Severity::Error,
+                        Diagnostic::at(module_path.escaped(), range),
+                    ));
+                }
+                tokens.push(Token { kind: TokenKind::Str(text), range });
+                continue;
+            }
+
+            if SYMBOLS.contains(&c) {
+                let range = Range {
+                    start: Location::new(line_number, col as u32),
+                    end: Location::new(line_number, col as u32 + 1),
+                };
+                tokens.push(Token { kind: TokenKind::Symbol(c), range });
+                col += 1;
+                continue;
+            }
+
+            let start = col;
+            let mut word = String::new();
+            while col < chars.len() && !chars[col].is_whitespace() && !SYMBOLS.contains(&chars[col]) && chars[col] != '"' {
+                word.push(chars[col]);
+                col += 1;
+            }
+            let range = Range {
+                start: Location::new(line_number, start as u32),
+                end: Location::new(line_number, col as u32),
+            };
+            tokens.push(Token { kind: TokenKind::Word(word), range });
+        }
+    }
+
+    DiagnosticResult::ok_with(tokens, messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_path as path;
+
+    fn words(tokens: &[Token]) -> Vec<TokenKind> {
+        tokens.iter().map(|token| token.kind.clone()).collect()
+    }
+
+    #[test]
+    fn lexes_words_and_symbols_with_their_ranges() {
+        let result = lex(&path("a"), vec![String::from("theorem t : true")]);
+
+        assert!(!result.messages().iter().any(|m| m.severity == Severity::Error));
+        let tokens = result.value().unwrap();
+        assert_eq!(
+            words(tokens),
+            vec![
+                TokenKind::Word(String::from("theorem")),
+                TokenKind::Word(String::from("t")),
+                TokenKind::Symbol(':'),
+                TokenKind::Word(String::from("true")),
+            ]
+        );
+        assert_eq!(tokens[1].range, Range { start: Location::new(0, 8), end: Location::new(0, 9) });
+    }
+
+    #[test]
+    fn an_unterminated_string_is_reported_and_the_rest_of_the_line_becomes_its_content() {
+        let result = lex(&path("a"), vec![String::from("include \"oops")]);
+
+        assert!(result.messages().iter().any(|m| m.severity == Severity::Error));
+        let tokens = result.value().unwrap();
+        assert_eq!(
+            words(tokens),
+            vec![TokenKind::Word(String::from("include")), TokenKind::Str(String::from("oops"))]
+        );
+    }
+
+    #[test]
+    fn lexing_continues_past_an_unterminated_string_on_an_earlier_line() {
+        let result = lex(&path("a"), vec![String::from("\"oops"), String::from("theorem t : true")]);
+
+        // Both the unterminated string on line one and the full statement on line two are
+        // lexed -- the error on line one doesn't stop line two from being processed.
+        assert_eq!(result.messages().len(), 1);
+        assert_eq!(
+            words(result.value().unwrap()),
+            vec![
+                TokenKind::Str(String::from("oops")),
+                TokenKind::Word(String::from("theorem")),
+                TokenKind::Word(String::from("t")),
+                TokenKind::Symbol(':'),
+                TokenKind::Word(String::from("true")),
+            ]
+        );
+    }
+}
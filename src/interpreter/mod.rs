@@ -25,12 +25,7 @@
 //! information between each other, ensuring (for example) that after a type check phase, all expressions
 //! actually have a type.
 
-use std::{
-    fmt::Display,
-    fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
-};
+use std::{fmt::Display, io::ErrorKind, path::PathBuf};
 
 use crate::{Diagnostic, DiagnosticResult, ErrorMessage, Severity};
 
@@ -85,10 +80,28 @@ impl Range {
     }
 }
 
-/// A list of path segments. These cannot contain forward or backward slashes, or colons.
+/// A list of path segments. See `module::ModulePath`'s doc comment: `Display` joins segments
+/// with `/`, but nothing stops a segment from containing one, so don't rely on that for anything
+/// that needs to round-trip -- use `escaped()` instead.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ModulePath(pub Vec<String>);
 
+impl ModulePath {
+    /// As `module::ModulePath::escaped`: a string key that's safe to use wherever two distinct
+    /// `ModulePath`s must never collide, such as `Diagnostic::module_path`.
+    pub fn escaped(&self) -> String {
+        crate::module::ModulePath::from(self).escaped()
+    }
+}
+
+/// A single-segment `ModulePath` fixture, shared by every pass's test module instead of each one
+/// forking its own copy of the same one-liner. As `module::test_path`, but for
+/// `interpreter::ModulePath`.
+#[cfg(test)]
+pub(crate) fn test_path(name: &str) -> ModulePath {
+    ModulePath(vec![name.to_string()])
+}
+
 impl<'a> From<&'a ModulePath> for PathBuf {
     fn from(path: &'a ModulePath) -> Self {
         path.0.iter().collect()
@@ -107,6 +120,15 @@ impl Display for ModulePath {
     }
 }
 
+/// `interpreter::ModulePath` and `module::ModulePath` are independent types with the same shape
+/// (a module predates the other, nothing ties them together at the type level), so this is a
+/// plain field copy rather than a real conversion.
+impl<'a> From<&'a ModulePath> for crate::module::ModulePath {
+    fn from(path: &'a ModulePath) -> Self {
+        crate::module::ModulePath(path.0.clone())
+    }
+}
+
 /// A fully qualified name referring to a top-level item declared in a `.shoumei` module.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct QualifiedName {
@@ -115,70 +137,166 @@ pub struct QualifiedName {
     pub range: Range,
 }
 
-pub fn parse(module_path: &ModulePath) -> DiagnosticResult<parser::ModuleP> {
+/// The debug-formatted output of a single compilation pass, keyed by the pass's name. This
+/// replaces printing intermediate results straight to stdout, so a caller such as the `lsp`
+/// subsystem can query what each pass produced instead of it spewing into the process's output.
+#[derive(Debug, Clone)]
+pub struct StageOutput {
+    pub stage: &'static str,
+    pub debug: String,
+}
+
+/// Everything running the full pipeline on a module produces: the parsed module itself, its
+/// qualified symbol table, and the declared type of each of its items. `module::ModuleLoader`
+/// keeps the `index` and `types` of this (in a `module::Module`) per module, so
+/// `lsp::LanguageServer::definition`/`hover` have something to resolve a position against
+/// without re-running the pipeline themselves.
+#[derive(Debug, Clone, Default)]
+pub struct Compiled {
+    pub module: parser::ModuleP,
+    pub index: index::IndexC,
+    pub types: types::TypesC,
+}
+
+pub fn parse(
+    module_path: &ModulePath,
+    provider: &dyn crate::module::SourceProvider,
+) -> DiagnosticResult<Compiled> {
+    parse_with_sources(module_path, provider).map(|(compiled, _lines)| compiled)
+}
+
+/// As `parse`, but also returns the source lines read for `module_path`, so that callers (such
+/// as `ModuleLoader`) can retain them for rendering diagnostics against the original source text.
+pub fn parse_with_sources(
+    module_path: &ModulePath,
+    provider: &dyn crate::module::SourceProvider,
+) -> DiagnosticResult<(Compiled, Vec<String>)> {
+    let mut trace = Vec::new();
+    parse_with_trace(module_path, provider, &mut trace)
+}
+
+/// As `parse_with_sources`, but also records a `StageOutput` for each of the `parser`, `types`
+/// and `index` passes that runs, so their results can be inspected without relying on stdout.
+/// `lexer`, `indent` and `brackets` record nothing.
+///
+/// Source text is read through `provider` rather than straight off disk, the same way
+/// `ModuleLoader::load` reads it, so a caller such as `lsp` can type-check unsaved editor
+/// buffers through this entry point too instead of only whatever is saved on disk.
+pub fn parse_with_trace(
+    module_path: &ModulePath,
+    provider: &dyn crate::module::SourceProvider,
+    trace: &mut Vec<StageOutput>,
+) -> DiagnosticResult<(Compiled, Vec<String>)> {
     // This chain of `bind`s is very similar to monadic `do` notation in Haskell.
-    // file <- ...
     // lines <- ...
-    let file = match File::open(PathBuf::from(module_path)) {
-        Ok(file) => file.into(),
-        Err(_) => {
-            let message = ErrorMessage::new(
-                String::from("cannot open file"),
+    let lines = match provider.read(&module_path.into()) {
+        Ok(lines) => DiagnosticResult::ok(lines),
+        Err(err) => {
+            let message = if err.kind() == ErrorKind::InvalidData {
+                err.to_string()
+            } else {
+                String::from("cannot open file")
+            };
+            DiagnosticResult::fail(ErrorMessage::new(
+                message,
                 Severity::Error,
-                Diagnostic::in_file(module_path.clone()),
-            );
-            DiagnosticResult::fail(message)
+                Diagnostic::in_file(module_path.escaped()),
+            ))
         }
     };
 
-    let lines = file.bind(|file| {
-        let mut lines = Vec::new();
-        for (line, line_number) in BufReader::new(file).lines().zip(0..) {
-            match line {
-                Ok(line) => {
-                    lines.push(line);
-                }
-                Err(_) => {
-                    return DiagnosticResult::fail(ErrorMessage::new(
-                        format!("file contained invalid UTF-8 on line {}", line_number + 1),
-                        Severity::Error,
-                        Diagnostic::in_file(module_path.clone()),
-                    ));
-                }
-            }
-        }
-        DiagnosticResult::ok(lines)
-    });
+    lines.bind(|lines| {
+        let source_lines = lines.clone();
+        compile(module_path, lines, trace).map(|compiled| (compiled, source_lines))
+    })
+}
 
-    // The use of `deny` means that any error in any compilation step will abort the compilation after the step is finished.
+/// As `parse_with_trace`, but for a caller that already has a module's source lines in hand
+/// instead of reading them from a `SourceProvider`. `module::ModuleLoader::load` is the reason
+/// this exists: it needs a module's lines anyway, to fingerprint them for incremental
+/// recompilation, so it calls this directly rather than having `parse_with_trace` read (and
+/// `ModuleLoader` retain) the same module's source twice.
+pub fn compile(
+    module_path: &ModulePath,
+    lines: Vec<String>,
+    trace: &mut Vec<StageOutput>,
+) -> DiagnosticResult<Compiled> {
+    // `lexer`, `indent`, `brackets` and `parser` each recover from an error within themselves (see
+    // their own doc comments for how -- an unterminated string, a stray or unclosed bracket, an
+    // unrecognised statement), so errors from `lexer`, `indent` and `brackets` accumulate without
+    // cutting the module short: a module with nothing but a stray bracket on line 1 and a valid theorem on
+    // line 2 still gets that theorem parsed, typed and indexed. There's no `bind(...).deny()`
+    // between those passes any more -- `deny` reads *every* message seen so far, not just the one
+    // the pass it's chained after just raised, so putting it there would zero out the recovered
+    // value over an earlier pass's already-reported (and already recovered-from) error.
+    //
+    // `parser::parse(...).deny()` below is different: `deny` runs on `parse`'s own fresh result,
+    // before its messages are merged into the rest, so it only reacts to errors `parser` itself
+    // raised this call, not ones `lexer`/`indent`/`brackets` already recovered from. That's the
+    // one gate that stays, because `types::compute_types` assumes a genuinely well-formed
+    // `ModuleP` to walk -- there's no recovery story for "keep computing types from a module
+    // `parser` already told us it couldn't make sense of".
 
-    lines
-        .bind(|lines| lexer::lex(module_path, lines))
-        .deny()
+    lexer::lex(module_path, lines)
         .bind(|tokens| indent::process_indent(module_path, tokens))
-        .deny()
         .bind(|token_block| brackets::process_brackets(module_path, token_block))
-        .deny()
-        .bind(|token_block| parser::parse(module_path, token_block))
-        .deny()
+        .bind(|token_block| parser::parse(module_path, token_block).deny())
         .bind(|module| {
-            println!("{:#?}", module);
+            trace.push(StageOutput {
+                stage: "parser",
+                debug: format!("{:#?}", module),
+            });
             let types = types::compute_types(module_path, &module);
-            println!("{:#?}", types);
-            let project_types = types.map(|types| {
-                let mut project_types = types::ProjectTypesC::new();
-                project_types.insert(module_path.clone(), types);
-                project_types
+            trace.push(StageOutput {
+                stage: "types",
+                debug: format!("{:#?}", types.value()),
             });
-            project_types.map(|project_types| (project_types, module))
+            types.map(|types| (module, types))
         })
-        .deny()
-        .bind(|(project_types, module)| {
+        .bind(|(module, types)| {
+            let mut project_types = types::ProjectTypesC::new();
+            project_types.insert(module_path.clone(), types.clone());
             let index = index::index(module_path, &module, &project_types);
-            println!("{:#?}", index);
-            index.map(|index| (project_types, index, module))
+            trace.push(StageOutput {
+                stage: "index",
+                debug: format!("{:#?}", index.value()),
+            });
+            index.map(|index| Compiled { module, index, types })
         })
-        .deny()
-        .map(|(project_types, index, module)| module)
-        .deny()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::module::{test_path as module_path, VirtualSources};
+
+    #[test]
+    fn independent_errors_from_different_passes_are_all_reported_in_one_run() {
+        let path = module_path("a");
+        let mut overlay = HashMap::new();
+        // Line 1's stray `)` is a `brackets` error; the second `theorem t` is an `index` error
+        // (name already declared). Neither is a `parser` error, so the `deny` that gates `types`
+        // never fires, and both independently-recovered-from errors should still show up together.
+        overlay.insert(
+            path.clone(),
+            String::from(")\ntheorem t : true\ntheorem t : false"),
+        );
+        let provider = VirtualSources(overlay);
+
+        let interpreter_path: ModulePath = (&path).into();
+        let result = parse(&interpreter_path, &provider);
+
+        assert_eq!(result.messages().len(), 2);
+        assert!(result.messages()[0].message.contains("unexpected closing bracket"));
+        assert!(result.messages()[1].message.contains("already declared"));
+
+        // Despite both earlier passes reporting errors, both theorems still made it all the way
+        // through to `types`, and `index` kept only the first (duplicate-free) declaration.
+        let compiled = result.value().unwrap();
+        assert_eq!(compiled.index.len(), 1);
+        assert_eq!(compiled.index[0].name, "t");
+        assert_eq!(compiled.types.get("t"), Some(&String::from("false")));
+    }
 }
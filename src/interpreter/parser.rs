@@ -0,0 +1,154 @@
+//! Parsing: turns an `indent::TokenBlock` into a `ModuleP`, the as-parsed syntax tree for a
+//! module (see `interpreter`'s module doc comment for the `P` suffix: no type checking has
+//! happened yet).
+//!
+//! The grammar recognised so far only covers two kinds of top-level (zero-indent) statement:
+//! - `include "path/to/module";`
+//! - `theorem <name> : <proposition tokens...>`
+//!
+//! A line matching neither is reported and skipped -- parsing resumes at the next top-level line
+//! rather than aborting the rest of the module.
+
+use super::indent::TokenBlock;
+use super::lexer::{Token, TokenKind};
+use super::{ModulePath, QualifiedName};
+use crate::{Diagnostic, DiagnosticResult, ErrorMessage, Severity};
+
+/// A `theorem` declaration as parsed. `statement` is the raw proposition text following the `:`,
+/// not yet validated or resolved against anything -- see `types::compute_types`, which is what
+/// turns this into the theorem's declared type.
+#[derive(Debug, Clone)]
+pub struct TheoremP {
+    pub name: QualifiedName,
+    pub statement: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModuleP {
+    pub includes: Vec<ModulePath>,
+    pub theorems: Vec<TheoremP>,
+}
+
+fn word(token: &Token) -> Option<&str> {
+    match &token.kind {
+        TokenKind::Word(word) => Some(word),
+        _ => None,
+    }
+}
+
+fn render_token(token: &Token) -> String {
+    match &token.kind {
+        TokenKind::Word(word) => word.clone(),
+        TokenKind::Str(text) => format!("\"{text}\""),
+        TokenKind::Symbol(c) => c.to_string(),
+    }
+}
+
+pub fn parse(module_path: &ModulePath, token_block: TokenBlock) -> DiagnosticResult<ModuleP> {
+    let mut module = ModuleP::default();
+    let mut messages = Vec::new();
+
+    for line in token_block.iter().filter(|line| line.indent == 0) {
+        let Some(first) = line.tokens.first() else {
+            continue;
+        };
+
+        match word(first) {
+            Some("include") => match line.tokens.get(1).map(|token| &token.kind) {
+                Some(TokenKind::Str(path)) => {
+                    module
+                        .includes
+                        .push(ModulePath(path.split('/').map(String::from).collect()));
+                }
+                _ => messages.push(ErrorMessage::new(
+                    String::from("expected a string path after `include`"),
+                    Severity::Error,
+                    Diagnostic::at(module_path.escaped(), first.range),
+                )),
+            },
+            Some("theorem") => match (line.tokens.get(1), line.tokens.get(2)) {
+                (Some(name_token), Some(colon_token))
+                    if word(name_token).is_some() && matches!(colon_token.kind, TokenKind::Symbol(':')) =>
+                {
+                    let name = word(name_token).unwrap().to_string();
+                    let statement = line.tokens[3..]
+                        .iter()
+                        .map(render_token)
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    module.theorems.push(TheoremP {
+                        name: QualifiedName {
+                            module_path: module_path.clone(),
+                            name,
+                            range: name_token.range,
+                        },
+                        statement,
+                    });
+                }
+                _ => messages.push(ErrorMessage::new(
+                    String::from("expected `theorem <name> : <proposition>`"),
+                    Severity::Error,
+                    Diagnostic::at(module_path.escaped(), first.range),
+                )),
+            },
+            _ => messages.push(ErrorMessage::new(
+                String::from("expected `include` or `theorem`"),
+                Severity::Error,
+                Diagnostic::at(module_path.escaped(), first.range),
+            )),
+        }
+    }
+
+    DiagnosticResult::ok_with(module, messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::brackets::process_brackets;
+    use super::super::indent::process_indent;
+    use super::super::lexer::lex;
+    use super::*;
+    use super::super::test_path as path;
+
+    fn token_block(lines: Vec<&str>) -> TokenBlock {
+        let tokens = lex(&path("a"), lines.into_iter().map(String::from).collect())
+            .into_parts()
+            .0
+            .unwrap();
+        let indented = process_indent(&path("a"), tokens).into_parts().0.unwrap();
+        process_brackets(&path("a"), indented).into_parts().0.unwrap()
+    }
+
+    #[test]
+    fn parses_an_include_directive() {
+        let result = parse(&path("a"), token_block(vec!["include \"other/module\";"]));
+
+        assert!(result.messages().is_empty());
+        let module = result.value().unwrap();
+        assert_eq!(module.includes, vec![ModulePath(vec![String::from("other"), String::from("module")])]);
+        assert!(module.theorems.is_empty());
+    }
+
+    #[test]
+    fn parses_a_theorem_declaration_with_its_statement() {
+        let result = parse(&path("a"), token_block(vec!["theorem t : true"]));
+
+        assert!(result.messages().is_empty());
+        let module = result.value().unwrap();
+        assert_eq!(module.theorems.len(), 1);
+        assert_eq!(module.theorems[0].name.name, "t");
+        assert_eq!(module.theorems[0].statement, "true");
+    }
+
+    #[test]
+    fn an_unrecognised_statement_is_reported_and_parsing_resumes_on_the_next_line() {
+        let result = parse(&path("a"), token_block(vec!["nonsense here", "theorem t : true"]));
+
+        assert_eq!(result.messages().len(), 1);
+        assert!(result.messages()[0].message.contains("expected `include` or `theorem`"));
+        // Parsing still picked up the valid statement on the following line.
+        let module = result.value().unwrap();
+        assert_eq!(module.theorems.len(), 1);
+        assert_eq!(module.theorems[0].name.name, "t");
+    }
+}
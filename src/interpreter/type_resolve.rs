@@ -0,0 +1,50 @@
+//! Resolves the identifier at a source position to the `QualifiedName`/declared-type pair it
+//! refers to, for `lsp::LanguageServer::definition` and `::hover`.
+//!
+//! This only looks at declarations, not uses: the grammar `parser` recognises has no way to
+//! reference another theorem from within a proposition yet, so resolving a position only ever
+//! finds the declaration whose own name the position falls inside, not a use of it elsewhere.
+
+use super::types::TypesC;
+use super::{Location, QualifiedName};
+
+pub fn resolve(index: &[QualifiedName], types: &TypesC, position: Location) -> Option<(QualifiedName, String)> {
+    let name = index
+        .iter()
+        .find(|name| name.range.start <= position && position < name.range.end)?;
+    let declared_type = types.get(&name.name)?;
+    Some((name.clone(), declared_type.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{ModulePath, Range};
+
+    fn name(text: &str, start_col: u32) -> QualifiedName {
+        QualifiedName {
+            module_path: ModulePath(vec![String::from("a")]),
+            name: String::from(text),
+            range: Range { start: Location::new(0, start_col), end: Location::new(0, start_col + text.len() as u32) },
+        }
+    }
+
+    #[test]
+    fn resolves_a_position_inside_a_declared_names_range() {
+        let index = vec![name("t", 8)];
+        let mut types = TypesC::new();
+        types.insert(String::from("t"), String::from("true"));
+
+        let (resolved, ty) = resolve(&index, &types, Location::new(0, 8)).unwrap();
+        assert_eq!(resolved.name, "t");
+        assert_eq!(ty, "true");
+    }
+
+    #[test]
+    fn a_position_outside_every_declared_name_resolves_to_nothing() {
+        let index = vec![name("t", 8)];
+        let types = TypesC::new();
+
+        assert!(resolve(&index, &types, Location::new(0, 0)).is_none());
+    }
+}
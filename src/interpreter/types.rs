@@ -0,0 +1,71 @@
+//! Computes the declared type of every theorem in a module.
+//!
+//! A `shoumei` theorem's type *is* the proposition it states -- the thing a later proof-checking
+//! pass would check a proof against -- so this is a direct copy of `parser::TheoremP::statement`,
+//! keyed by name, rather than inference over an expression language the parser doesn't model yet.
+
+use std::collections::HashMap;
+
+use super::parser::ModuleP;
+use super::ModulePath;
+use crate::DiagnosticResult;
+
+/// The types computed so far for a single module's theorems, keyed by (unqualified) name. See
+/// `interpreter`'s module doc comment for the `C` suffix: this is an intermediate cache, built
+/// before `index` turns it into qualified, resolvable entries.
+pub type TypesC = HashMap<String, String>;
+
+/// The `TypesC` computed for every module processed so far, keyed by module path -- so that once
+/// the grammar can reference a name from another module, `index` has something to resolve it
+/// against without needing a new parameter.
+#[derive(Debug, Clone, Default)]
+pub struct ProjectTypesC(HashMap<ModulePath, TypesC>);
+
+impl ProjectTypesC {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, module_path: ModulePath, types: TypesC) {
+        self.0.insert(module_path, types);
+    }
+
+    pub fn get(&self, module_path: &ModulePath) -> Option<&TypesC> {
+        self.0.get(module_path)
+    }
+}
+
+pub fn compute_types(_module_path: &ModulePath, module: &ModuleP) -> DiagnosticResult<TypesC> {
+    let types = module
+        .theorems
+        .iter()
+        .map(|theorem| (theorem.name.name.clone(), theorem.statement.clone()))
+        .collect();
+    DiagnosticResult::ok(types)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_path as path;
+    use crate::interpreter::parser::TheoremP;
+    use crate::interpreter::{Location, QualifiedName, Range};
+
+    #[test]
+    fn a_theorems_type_is_the_proposition_it_states() {
+        let module = ModuleP {
+            includes: Vec::new(),
+            theorems: vec![TheoremP {
+                name: QualifiedName {
+                    module_path: path("a"),
+                    name: String::from("t"),
+                    range: Range::from(Location::new(0, 0)),
+                },
+                statement: String::from("true"),
+            }],
+        };
+
+        let types = compute_types(&path("a"), &module).into_parts().0.unwrap();
+        assert_eq!(types.get("t"), Some(&String::from("true")));
+    }
+}
@@ -0,0 +1,16 @@
+//! A small incremental compiler and language-server backend for a toy proof language, "shoumei".
+//! `module::ModuleLoader` drives `interpreter`'s compilation passes over each module, caching
+//! fingerprints and dependencies (`incremental`) so unchanged modules aren't rebuilt, and `lsp`
+//! exposes the result as `textDocument/publishDiagnostics`, `definition` and `hover`.
+//!
+//! Only declarations are resolved, not uses: the grammar `interpreter::parser` recognises has no
+//! way to reference another theorem from within a proposition yet (see `interpreter::type_resolve`'s
+//! doc comment), so cross-module navigation isn't implemented.
+
+pub mod diagnostic;
+pub mod incremental;
+pub mod interpreter;
+pub mod lsp;
+pub mod module;
+
+pub use diagnostic::{Diagnostic, DiagnosticResult, ErrorEmitter, ErrorMessage, Label, LabelStyle, Severity};
@@ -0,0 +1,264 @@
+//! A language-server subsystem built on top of `ModuleLoader`: it translates accumulated
+//! `ErrorMessage`s into `textDocument/publishDiagnostics`-shaped values, and answers
+//! `textDocument/definition` and `textDocument/hover` from the `index`/`types` that
+//! `ModuleLoader::load` now computes for every module.
+//!
+//! This module only models the request/response shapes and the state machine behind them; it
+//! does not speak the LSP wire protocol (JSON-RPC framing over stdio) itself, since that's a
+//! transport concern independent of what a request actually resolves to.
+
+use std::collections::HashMap;
+
+use crate::{
+    diagnostic::{ErrorMessage, Severity},
+    interpreter::{Location, QualifiedName, Range},
+    module::{FileSystemSources, ModuleLoader, ModulePath, SourceProvider},
+    ErrorEmitter,
+};
+
+/// A UTF-16-agnostic line/column position, matching `lsp-types`' `Position`. Columns internally
+/// count `char`s (see `interpreter::lexer::lex` and `interpreter::Location`), and this is a
+/// direct copy of them rather than a real encoding conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LspPosition {
+    pub line: u32,
+    pub character: u32,
+}
+
+impl From<Location> for LspPosition {
+    fn from(location: Location) -> Self {
+        Self {
+            line: location.line,
+            character: location.col,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LspRange {
+    pub start: LspPosition,
+    pub end: LspPosition,
+}
+
+impl From<LspPosition> for Location {
+    fn from(position: LspPosition) -> Self {
+        Self::new(position.line, position.character)
+    }
+}
+
+impl From<Range> for LspRange {
+    fn from(range: Range) -> Self {
+        Self {
+            start: range.start.into(),
+            end: range.end.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LspSeverity {
+    Error,
+    Warning,
+    Information,
+}
+
+impl From<Severity> for LspSeverity {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Error => LspSeverity::Error,
+            Severity::Warning => LspSeverity::Warning,
+            Severity::Note => LspSeverity::Information,
+        }
+    }
+}
+
+/// One entry of a `textDocument/publishDiagnostics` notification.
+#[derive(Debug, Clone)]
+pub struct PublishedDiagnostic {
+    pub range: Option<LspRange>,
+    pub severity: LspSeverity,
+    pub message: String,
+}
+
+fn published_diagnostics(module_path: &str, messages: &[ErrorMessage]) -> Vec<PublishedDiagnostic> {
+    messages
+        .iter()
+        .filter(|message| message.diagnostic.module_path == module_path)
+        .map(|message| PublishedDiagnostic {
+            range: message
+                .diagnostic
+                .primary_label()
+                .map(|label| label.range.into()),
+            severity: message.severity.into(),
+            message: message.message.clone(),
+        })
+        .collect()
+}
+
+/// A location resolved by `textDocument/definition`: the module and range a `QualifiedName`
+/// points at. Note this carries `interpreter::ModulePath`, the type `QualifiedName` is indexed
+/// by, rather than `module::ModulePath`, the type `ModuleLoader` is keyed by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LspLocation {
+    pub module_path: crate::interpreter::ModulePath,
+    pub range: LspRange,
+}
+
+impl From<&QualifiedName> for LspLocation {
+    fn from(name: &QualifiedName) -> Self {
+        Self {
+            module_path: name.module_path.clone(),
+            range: name.range.into(),
+        }
+    }
+}
+
+/// Reads from an in-memory overlay of unsaved editor buffers first, falling back to disk for
+/// every module that isn't currently open. This is what lets the language server type-check
+/// live edits without the user having to save first.
+struct OverlaySources {
+    overlay: HashMap<ModulePath, String>,
+    disk: FileSystemSources,
+}
+
+impl SourceProvider for OverlaySources {
+    fn read(&self, path: &ModulePath) -> std::io::Result<Vec<String>> {
+        match self.overlay.get(path) {
+            Some(text) => Ok(text.lines().map(String::from).collect()),
+            None => self.disk.read(path),
+        }
+    }
+}
+
+/// Drives a `ModuleLoader` on behalf of an editor: tracks open buffers, reprocesses the edited
+/// module (and, via the incremental layer, whatever depends on it) on every change, and answers
+/// queries against the result.
+pub struct LanguageServer {
+    loader: ModuleLoader,
+    buffers: HashMap<ModulePath, String>,
+}
+
+impl LanguageServer {
+    pub fn new() -> Self {
+        Self {
+            loader: ModuleLoader::new(ErrorEmitter::default()),
+            buffers: HashMap::new(),
+        }
+    }
+
+    /// Handles `textDocument/didOpen` and `textDocument/didChange`: the buffer's unsaved text
+    /// takes priority over disk until the document is closed.
+    pub fn did_change(&mut self, module_path: ModulePath, text: String) {
+        self.buffers.insert(module_path.clone(), text);
+        self.reload(module_path);
+    }
+
+    /// Handles `textDocument/didClose`: once a buffer is closed, we fall back to whatever is
+    /// saved on disk for that module.
+    pub fn did_close(&mut self, module_path: ModulePath) {
+        self.buffers.remove(&module_path);
+        self.reload(module_path);
+    }
+
+    /// Reprocesses `module_path` and, transitively, every module that depends on it, so an edit
+    /// to a widely-included module invalidates everything downstream of it, not just itself.
+    fn reload(&mut self, module_path: ModulePath) {
+        self.loader.set_provider(Box::new(OverlaySources {
+            overlay: self.buffers.clone(),
+            disk: FileSystemSources,
+        }));
+
+        // Each edit is its own incremental run: only dependencies recompiled as part of
+        // reprocessing `module_path` (and its dependents, below) should force their own
+        // dependents stale, not every module recompiled since the server started.
+        self.loader.begin_run();
+
+        let mut queue = vec![module_path];
+        let mut queued: std::collections::HashSet<ModulePath> = queue.iter().cloned().collect();
+        while let Some(next) = queue.pop() {
+            self.loader.load(next.clone());
+            for dependent in self.loader.direct_dependents(&next) {
+                if queued.insert(dependent.clone()) {
+                    queue.push(dependent);
+                }
+            }
+        }
+    }
+
+    /// Answers `textDocument/publishDiagnostics` for `module_path`.
+    pub fn diagnostics(&self, module_path: &ModulePath) -> Vec<PublishedDiagnostic> {
+        published_diagnostics(&module_path.escaped(), self.loader.messages())
+    }
+
+    /// Answers `textDocument/definition` by resolving the identifier under `position` to the
+    /// `QualifiedName.range` produced by the `index` pass. `None` both for a module that was
+    /// never loaded (or failed to parse) and for a position that isn't inside any declared name.
+    pub fn definition(&self, module_path: &ModulePath, position: LspPosition) -> Option<LspLocation> {
+        let module = self.loader.module(module_path)?;
+        let (name, _) = crate::interpreter::type_resolve::resolve(&module.index, &module.types, position.into())?;
+        Some(LspLocation::from(&name))
+    }
+
+    /// Answers `textDocument/hover` with the type deduced for the identifier under `position`, as
+    /// `definition` but returning the declared type instead of the declaration's own location.
+    pub fn hover(&self, module_path: &ModulePath, position: LspPosition) -> Option<String> {
+        let module = self.loader.module(module_path)?;
+        let (_, declared_type) =
+            crate::interpreter::type_resolve::resolve(&module.index, &module.types, position.into())?;
+        Some(declared_type)
+    }
+}
+
+impl Default for LanguageServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::test_path as path;
+
+    #[test]
+    fn fixing_a_module_clears_its_stale_diagnostics() {
+        let mut server = LanguageServer::new();
+        let missing = path("does-not-exist-on-disk");
+
+        // No overlay buffer and nothing on disk: this reload raises "cannot open file".
+        server.did_close(missing.clone());
+        assert!(!server.diagnostics(&missing).is_empty());
+
+        // Opening it with real content should clear the earlier diagnostic, not just add to it.
+        server.did_change(missing.clone(), String::from("theorem t : true"));
+        assert!(server.diagnostics(&missing).is_empty());
+    }
+
+    #[test]
+    fn a_position_outside_every_declared_name_resolves_to_none() {
+        let mut server = LanguageServer::new();
+        let module_path = path("does-not-exist-on-disk");
+        server.did_change(module_path.clone(), String::from("theorem t : true"));
+
+        // Column 0 falls on the `theorem` keyword, not the `t` identifier's own range.
+        let position = LspPosition { line: 0, character: 0 };
+        assert_eq!(server.definition(&module_path, position), None);
+        assert_eq!(server.hover(&module_path, position), None);
+    }
+
+    #[test]
+    fn definition_and_hover_resolve_the_identifier_under_the_cursor() {
+        let mut server = LanguageServer::new();
+        let module_path = path("does-not-exist-on-disk");
+        server.did_change(module_path.clone(), String::from("theorem t : true"));
+
+        // Column 8 of "theorem t : true" is the `t` identifier.
+        let position = LspPosition { line: 0, character: 8 };
+
+        let definition = server.definition(&module_path, position).unwrap();
+        assert_eq!(definition.module_path, crate::interpreter::ModulePath::from(&module_path));
+        assert_eq!(definition.range.start, LspPosition { line: 0, character: 8 });
+
+        assert_eq!(server.hover(&module_path, position), Some(String::from("true")));
+    }
+}
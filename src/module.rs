@@ -2,13 +2,21 @@ use std::{
     collections::{HashMap, HashSet},
     fmt::Display,
     fs::File,
+    io,
     io::{BufRead, BufReader},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
-use crate::{Diagnostic, DiagnosticResult, ErrorEmitter, ErrorMessage, Severity};
+use crate::{
+    incremental::IncrementalCache, Diagnostic, DiagnosticResult, ErrorEmitter, ErrorMessage,
+    Severity,
+};
 
-/// A list of path segments. These cannot contain forward or backward slashes, or colons.
+/// A list of path segments. `Display` joins them with `/`, so a segment containing a slash
+/// renders ambiguously with a genuine multi-segment path -- but nothing enforces that here, since
+/// the field is public. Never use `Display`/`to_string()` as a key two different `ModulePath`s
+/// must never collide on (a `HashMap` key, `Diagnostic::module_path`, the `incremental` sidecar);
+/// use `escaped()` for that instead.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ModulePath(pub Vec<String>);
 
@@ -18,6 +26,15 @@ impl<'a> From<&'a ModulePath> for PathBuf {
     }
 }
 
+/// `module::ModulePath` and `interpreter::ModulePath` are independent types with the same shape
+/// (see `interpreter::ModulePath`'s own reverse impl), so this is a plain field copy rather than
+/// a real conversion.
+impl<'a> From<&'a ModulePath> for crate::interpreter::ModulePath {
+    fn from(path: &'a ModulePath) -> Self {
+        crate::interpreter::ModulePath(path.0.clone())
+    }
+}
+
 impl Display for ModulePath {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for (i, item) in self.0.iter().enumerate() {
@@ -30,11 +47,88 @@ impl Display for ModulePath {
     }
 }
 
+/// The character `escaped`/`from_escaped` use to make escaping reversible: `%` followed by a
+/// segment's escaped character's code point, followed by another `%`.
+const ESCAPE: char = '%';
+
+fn escape_segment(segment: &str) -> String {
+    let mut escaped = String::with_capacity(segment.len());
+    for c in segment.chars() {
+        match c {
+            '%' | '/' | ':' | '\t' => {
+                escaped.push(ESCAPE);
+                escaped.push_str(&(c as u32).to_string());
+                escaped.push(ESCAPE);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_segment(segment: &str) -> String {
+    let mut unescaped = String::with_capacity(segment.len());
+    let mut chars = segment.chars();
+    while let Some(c) = chars.next() {
+        if c != ESCAPE {
+            unescaped.push(c);
+            continue;
+        }
+        let code: String = chars.by_ref().take_while(|&c| c != ESCAPE).collect();
+        match code.parse::<u32>().ok().and_then(char::from_u32) {
+            Some(c) => unescaped.push(c),
+            None => unescaped.push_str(&code),
+        }
+    }
+    unescaped
+}
+
+impl ModulePath {
+    /// A string key that's safe to use wherever two distinct `ModulePath`s must never collide --
+    /// a `HashMap` key, `Diagnostic::module_path`, the `incremental` sidecar format. Plain
+    /// `Display`/`to_string()` joins segments with `/` with nothing stopping a segment from
+    /// containing one itself (see this struct's doc comment), so two different paths can render
+    /// identically; this escapes `%`, `/`, `:` and `\t` within each segment first (the same
+    /// characters the `incremental` sidecar format needs protected, plus `%` itself so the
+    /// escaping is reversible), so the result uniquely determines the original path. For ordinary
+    /// segments (no reserved characters) this is identical to `Display`'s output.
+    pub fn escaped(&self) -> String {
+        self.0
+            .iter()
+            .map(|segment| escape_segment(segment))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// The inverse of `escaped`.
+    pub fn from_escaped(text: &str) -> Self {
+        ModulePath(text.split('/').map(unescape_segment).collect())
+    }
+}
+
+/// A single-segment `ModulePath` fixture, shared by this module's, `incremental`'s and `lsp`'s
+/// test modules instead of each forking its own copy of the same one-liner.
+#[cfg(test)]
+pub(crate) fn test_path(name: &str) -> ModulePath {
+    ModulePath(vec![name.to_string()])
+}
+
 /// A single `.shoumei` file is called a module. It may export theorems, proofs, definitions, etc.
 /// This `Module` struct contains the parsed abstract syntax tree of a module.
 /// Module inclusions must be hierarchical and non-circular. This prevents circular proofs.
-#[derive(Debug, Clone)]
-pub struct Module {}
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    /// The modules this module directly includes. `ModuleLoader::direct_dependents` follows
+    /// these edges to find what to reprocess when a module changes; the incremental cache
+    /// records the transitive closure of them.
+    pub includes: Vec<ModulePath>,
+    /// The qualified names this module declares, as computed by `interpreter::index`. Empty for
+    /// a module that failed to parse.
+    pub index: crate::interpreter::index::IndexC,
+    /// The declared type of each of this module's items, as computed by
+    /// `interpreter::types::compute_types`. Empty for a module that failed to parse.
+    pub types: crate::interpreter::types::TypesC,
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct Location {
@@ -50,6 +144,58 @@ impl Location {
     }
 }
 
+/// Something that can supply the source lines for a module, without necessarily reading them
+/// from disk. `ModuleLoader` defaults to a `FileSystemSources`, but it can be given a
+/// `VirtualSources` to serve in-memory buffers instead, the same way rustc separates
+/// `parse_from_file` from `parse_from_source_str`. `VirtualSources` never falls back to disk: a
+/// module with no registered buffer fails to read.
+pub trait SourceProvider {
+    fn read(&self, path: &ModulePath) -> io::Result<Vec<String>>;
+}
+
+/// Reads modules from disk, as `ModuleLoader` did before `SourceProvider` existed.
+pub struct FileSystemSources;
+
+impl SourceProvider for FileSystemSources {
+    fn read(&self, path: &ModulePath) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        for (line, line_number) in BufReader::new(File::open(PathBuf::from(path))?)
+            .lines()
+            .zip(0..)
+        {
+            match line {
+                Ok(line) => lines.push(line),
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("file contained invalid UTF-8 on line {}", line_number + 1),
+                    ));
+                }
+            }
+        }
+        Ok(lines)
+    }
+}
+
+/// Serves module source text from an in-memory overlay instead of disk, for unit tests and for
+/// editors whose unsaved buffers have diverged from the file on disk.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualSources(pub HashMap<ModulePath, String>);
+
+impl SourceProvider for VirtualSources {
+    fn read(&self, path: &ModulePath) -> io::Result<Vec<String>> {
+        self.0.get(path).map_or_else(
+            || {
+                Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no virtual source registered for module {path}"),
+                ))
+            },
+            |source| Ok(source.lines().map(String::from).collect()),
+        )
+    }
+}
+
 /// Loads resources from disk, lexing and parsing them.
 pub struct ModuleLoader {
     /// When we begin loading a module, this set is updated. When a module is fully loaded, the corresponding value is removed.
@@ -58,6 +204,21 @@ pub struct ModuleLoader {
     /// A map containing all lexed and parsed modules.
     /// If a module could not be parsed, the result here is None to show that
     modules: HashMap<ModulePath, Option<Module>>,
+    /// The source lines read for each module, kept around so diagnostics can be rendered with
+    /// the offending source text instead of just a bare message. Keyed by `ModulePath::escaped`,
+    /// matching `Diagnostic::module_path`, not `Display`/`to_string()` -- see `ModulePath`'s doc
+    /// comment for why those two differ.
+    sources: HashMap<String, Vec<String>>,
+    /// Where module source text is read from. Defaults to `FileSystemSources`; swap it out with
+    /// `with_provider` to serve overlay buffers instead.
+    provider: Box<dyn SourceProvider>,
+    /// Fingerprints and dependency edges from the last time each module was compiled, used to
+    /// skip recompiling modules that haven't changed and whose dependencies haven't either.
+    cache: IncrementalCache,
+    /// Modules actually recompiled so far this run, as opposed to reused from the cache. A
+    /// module depending on one of these must be recompiled too, even if its own source didn't
+    /// change.
+    recompiled_this_run: HashSet<ModulePath>,
     error_emitter: ErrorEmitter,
 }
 
@@ -66,54 +227,144 @@ impl ModuleLoader {
         Self {
             currently_loading: HashSet::new(),
             modules: HashMap::new(),
+            sources: HashMap::new(),
+            provider: Box::new(FileSystemSources),
+            cache: IncrementalCache::new(),
+            recompiled_this_run: HashSet::new(),
             error_emitter,
         }
     }
 
+    /// Overrides the source provider, for example to register virtual buffers that take
+    /// priority over whatever is saved on disk.
+    pub fn with_provider(mut self, provider: Box<dyn SourceProvider>) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    /// As `with_provider`, but for loaders that are kept around and re-used, such as the `lsp`
+    /// subsystem's, where the overlay buffers change on every keystroke.
+    pub fn set_provider(&mut self, provider: Box<dyn SourceProvider>) {
+        self.provider = provider;
+    }
+
+    /// Every diagnostic emitted so far, without consuming them the way `take_error_emitter` does.
+    pub fn messages(&self) -> &[ErrorMessage] {
+        self.error_emitter.messages()
+    }
+
+    /// Starts a fresh incremental run: `is_stale` only treats a module as invalidated-by-a-
+    /// dependency if that dependency was recompiled *since the last call to `begin_run`*. A
+    /// long-lived loader (such as the `lsp` subsystem's) must call this once per edit, otherwise
+    /// every module ever recompiled stays flagged as "recompiled this run" forever, and anything
+    /// depending on it is forced stale on every subsequent load.
+    pub fn begin_run(&mut self) {
+        self.recompiled_this_run.clear();
+    }
+
+    /// The modules already loaded whose `includes` directly names `module_path` — i.e. the
+    /// modules that must be reprocessed when `module_path` changes.
+    pub fn direct_dependents(&self, module_path: &ModulePath) -> Vec<ModulePath> {
+        self.modules
+            .iter()
+            .filter_map(|(path, module)| {
+                let module = module.as_ref()?;
+                module.includes.contains(module_path).then(|| path.clone())
+            })
+            .collect()
+    }
+
+    /// Loads the fingerprint/dependency cache left behind by a previous invocation. Compiled
+    /// modules are not part of it, so a module not yet in memory is still compiled on its first
+    /// `load` even if the sidecar says it is unchanged. A missing or unreadable sidecar file just
+    /// yields an empty cache, not an error.
+    pub fn load_cache(&mut self, sidecar: &Path) {
+        self.cache = IncrementalCache::load(sidecar);
+    }
+
+    /// Persists the fingerprint/dependency cache so a future cold start can reuse it.
+    pub fn save_cache(&self, sidecar: &Path) -> io::Result<()> {
+        self.cache.save(sidecar)
+    }
+
     /// Any errors or other messages while loading are emitted to the given ErrorEmitter.
     pub fn load(&mut self, module_path: ModulePath) {
         if self.currently_loading.contains(&module_path) {
             self.error_emitter.process(vec![ErrorMessage::new(
                 String::from("cyclic module inclusion detected"),
                 Severity::Error,
-                Diagnostic::in_file(module_path),
+                Diagnostic::in_file(module_path.escaped()),
             )]);
             return;
         }
         self.currently_loading.insert(module_path.clone());
 
-        let file = match File::open(PathBuf::from(&module_path)) {
-            Ok(file) => file.into(),
-            Err(_) => {
-                let message = ErrorMessage::new(
-                    String::from("cannot open file"),
+        // A long-lived loader (the `lsp` subsystem's) reloads the same module over and over as
+        // the user edits; without this, every diagnostic ever raised against it would still be
+        // there after the source was fixed.
+        self.error_emitter.clear_module(&module_path.escaped());
+
+        let lines = match self.provider.read(&module_path) {
+            Ok(lines) => DiagnosticResult::ok(lines),
+            Err(err) => {
+                let message = if err.kind() == io::ErrorKind::InvalidData {
+                    err.to_string()
+                } else {
+                    String::from("cannot open file")
+                };
+                DiagnosticResult::fail(ErrorMessage::new(
+                    message,
                     Severity::Error,
-                    Diagnostic::in_file(module_path.clone()),
-                );
-                DiagnosticResult::fail(message)
+                    Diagnostic::in_file(module_path.escaped()),
+                ))
             }
         };
 
-        let lines = file.bind(|file| {
-            let mut lines = Vec::new();
-            for (line, line_number) in BufReader::new(file).lines().zip(0..) {
-                match line {
-                    Ok(line) => {
-                        lines.push(line);
-                    }
-                    Err(_) => {
-                        return DiagnosticResult::fail(ErrorMessage::new(
-                            format!("file contained invalid UTF-8 on line {}", line_number + 1),
-                            Severity::Error,
-                            Diagnostic::in_file(module_path.clone()),
-                        ));
-                    }
+        let module = lines.bind(|lines| {
+            let fingerprint = crate::incremental::fingerprint(&lines);
+            self.sources.insert(module_path.escaped(), lines.clone());
+
+            // Reverifying a module whose source and dependencies are both unchanged since the
+            // last time we saw it is pure waste, so only run the interpreter pipeline again if
+            // the cache says it's stale. The cache hit below reuses whatever `Module` we already
+            // have in memory for this path rather than rebuilding a fresh one, since that's the
+            // actual work (lexing, parsing, indexing) `is_stale` exists to let us skip.
+            if !self.cache.is_stale(&module_path, fingerprint, &self.recompiled_this_run) {
+                if let Some(module) = self.modules.get(&module_path).cloned().flatten() {
+                    return DiagnosticResult::ok(module);
                 }
             }
-            DiagnosticResult::ok(lines)
-        });
 
-        let module = lines.bind(|_| DiagnosticResult::ok(Module {}));
+            // `lines` above is fed straight into `compile` rather than going through
+            // `interpreter::parse`, which would read `module_path` from `self.provider` all over
+            // again -- we already have its source text in hand for fingerprinting, so there's no
+            // reason to read it from the provider a second time.
+            let interpreter_path: crate::interpreter::ModulePath = (&module_path).into();
+            let mut trace = Vec::new();
+            crate::interpreter::compile(&interpreter_path, lines, &mut trace).map(|compiled| {
+                let includes: Vec<ModulePath> =
+                    compiled.module.includes.iter().map(ModulePath::from).collect();
+                // Extend the direct includes with the deps already recorded for each of them, so
+                // the cache holds every module this one transitively depends on.
+                let mut deps = includes.clone();
+                let mut i = 0;
+                while i < deps.len() {
+                    for dep in self.cache.deps(&deps[i]).to_vec() {
+                        if !deps.contains(&dep) {
+                            deps.push(dep);
+                        }
+                    }
+                    i += 1;
+                }
+                self.cache.record(module_path.clone(), fingerprint, deps);
+                self.recompiled_this_run.insert(module_path.clone());
+                Module {
+                    includes,
+                    index: compiled.index,
+                    types: compiled.types,
+                }
+            })
+        });
 
         let module = self.error_emitter.consume_diagnostic(module);
 
@@ -121,8 +372,158 @@ impl ModuleLoader {
         self.modules.insert(module_path, module);
     }
 
+    /// The last successfully loaded `Module` for `module_path`, if any -- `None` both for a
+    /// module never loaded and for one that failed to parse. Used by `lsp::LanguageServer` to
+    /// resolve `definition`/`hover` against a module's `index`/`types` without re-running the
+    /// interpreter pipeline itself.
+    pub fn module(&self, module_path: &ModulePath) -> Option<&Module> {
+        self.modules.get(module_path)?.as_ref()
+    }
+
     /// Call this to retrieve all errors emitted while loading the modules.
     pub fn take_error_emitter(&mut self) -> ErrorEmitter {
         std::mem::take(&mut self.error_emitter)
     }
+
+    /// Renders every diagnostic raised so far against the source text retained while loading,
+    /// falling back to a plain message for diagnostics whose module's source was never read
+    /// (for example, a module that could not be opened at all).
+    pub fn render_errors(&self) -> String {
+        crate::diagnostic::render::render(self.error_emitter.messages(), &self.sources)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use super::test_path as path;
+
+    /// A `SourceProvider` that counts how many times each module was read, so a test can tell
+    /// whether a module was actually reprocessed rather than served from `ModuleLoader`'s cache.
+    #[derive(Default)]
+    struct CountingSources {
+        sources: HashMap<ModulePath, String>,
+        reads: Rc<RefCell<HashMap<ModulePath, u32>>>,
+    }
+
+    impl SourceProvider for CountingSources {
+        fn read(&self, path: &ModulePath) -> io::Result<Vec<String>> {
+            *self.reads.borrow_mut().entry(path.clone()).or_insert(0) += 1;
+            self.sources.get(path).map_or_else(
+                || Err(io::Error::new(io::ErrorKind::NotFound, "missing")),
+                |source| Ok(source.lines().map(String::from).collect()),
+            )
+        }
+    }
+
+    #[test]
+    fn loads_from_a_virtual_source_instead_of_disk() {
+        let mut overlay = HashMap::new();
+        overlay.insert(path("does-not-exist-on-disk"), String::from("theorem t : true"));
+
+        let mut loader = ModuleLoader::new(ErrorEmitter::default())
+            .with_provider(Box::new(VirtualSources(overlay)));
+        loader.load(path("does-not-exist-on-disk"));
+
+        assert!(!loader.take_error_emitter().has_errors());
+    }
+
+    #[test]
+    fn reports_an_error_for_a_module_missing_from_the_overlay() {
+        let mut loader =
+            ModuleLoader::new(ErrorEmitter::default()).with_provider(Box::new(VirtualSources::default()));
+        loader.load(path("unregistered"));
+
+        assert!(loader.take_error_emitter().has_errors());
+    }
+
+    #[test]
+    fn loading_populates_the_modules_index_and_types() {
+        let mut overlay = HashMap::new();
+        overlay.insert(path("a"), String::from("theorem t : true"));
+
+        let mut loader = ModuleLoader::new(ErrorEmitter::default())
+            .with_provider(Box::new(VirtualSources(overlay)));
+        loader.load(path("a"));
+
+        assert!(!loader.take_error_emitter().has_errors());
+        let module = loader.module(&path("a")).unwrap();
+        assert!(module.includes.is_empty());
+        assert_eq!(module.index.len(), 1);
+        assert_eq!(module.index[0].name, "t");
+        assert_eq!(module.types.get("t"), Some(&String::from("true")));
+    }
+
+    #[test]
+    fn a_second_load_of_an_unchanged_module_reuses_the_cached_result() {
+        let mut overlay = HashMap::new();
+        overlay.insert(path("a"), String::from("theorem t : true"));
+
+        let mut loader = ModuleLoader::new(ErrorEmitter::default())
+            .with_provider(Box::new(VirtualSources(overlay)));
+        loader.load(path("a"));
+        // Start a fresh run so the second load's recompilation (if any) is the only one recorded.
+        loader.begin_run();
+        loader.load(path("a"));
+
+        assert!(!loader.take_error_emitter().has_errors());
+        assert!(!loader.recompiled_this_run.contains(&path("a")));
+        assert_eq!(loader.module(&path("a")).unwrap().index.len(), 1);
+    }
+
+    #[test]
+    fn changing_an_included_modules_source_recompiles_its_dependent_too() {
+        // `a` includes `b`; this is the scenario the dependents cascade
+        // (`direct_dependents`/`begin_run`, driven by `lsp::LanguageServer::reload`) exists for:
+        // an edit to `b` must also recompile `a`, not just `b` itself.
+        let reads = Rc::new(RefCell::new(HashMap::new()));
+        let mut sources = HashMap::new();
+        sources.insert(path("a"), String::from("include \"b\";"));
+        sources.insert(path("b"), String::from("theorem t : true"));
+        let provider = CountingSources {
+            sources,
+            reads: reads.clone(),
+        };
+
+        let mut loader = ModuleLoader::new(ErrorEmitter::default()).with_provider(Box::new(provider));
+
+        // Load both modules once so `a`'s recorded `includes` (and hence `direct_dependents`)
+        // are populated.
+        loader.load(path("b"));
+        loader.load(path("a"));
+        assert!(!loader.take_error_emitter().has_errors());
+        assert_eq!(loader.direct_dependents(&path("b")), vec![path("a")]);
+
+        // Change `b`'s source, then reprocess only `b`, following dependents exactly as
+        // `lsp::LanguageServer::reload` does.
+        let mut sources = HashMap::new();
+        sources.insert(path("a"), String::from("include \"b\";"));
+        sources.insert(path("b"), String::from("theorem t : false"));
+        loader.set_provider(Box::new(CountingSources {
+            sources,
+            reads: reads.clone(),
+        }));
+        loader.begin_run();
+        reads.borrow_mut().clear();
+        let mut queue = vec![path("b")];
+        let mut queued: HashSet<ModulePath> = queue.iter().cloned().collect();
+        while let Some(next) = queue.pop() {
+            loader.load(next.clone());
+            for dependent in loader.direct_dependents(&next) {
+                if queued.insert(dependent.clone()) {
+                    queue.push(dependent);
+                }
+            }
+        }
+
+        // `a`'s own source never changed, but it must still have been read (and recompiled)
+        // because its dependency `b` was -- not silently left with a stale cached result.
+        assert_eq!(*reads.borrow().get(&path("a")).unwrap_or(&0), 1);
+        assert_eq!(*reads.borrow().get(&path("b")).unwrap_or(&0), 1);
+        assert!(loader.recompiled_this_run.contains(&path("b")));
+        assert!(loader.recompiled_this_run.contains(&path("a")));
+        assert!(!loader.messages().iter().any(|m| m.severity == Severity::Error));
+    }
 }